@@ -5,12 +5,19 @@ use std::{
     mem,
     ops::{Deref, DerefMut},
     os::unix::{io::IntoRawFd, prelude::RawFd},
+    path::Path,
 };
 
 use crate::{
     generated::bpf_map_type::BPF_MAP_TYPE_ARRAY_OF_MAPS,
-    maps::{of_maps::MapOfMaps, Map, MapError, MapKeys, MapRef, MapRefMut},
-    sys::{bpf_map_delete_elem, bpf_map_get_fd_by_id, bpf_map_lookup_elem, bpf_map_update_elem},
+    maps::{
+        of_maps::{InnerMapBtf, MapOfMaps},
+        Map, MapError, MapKeys, MapRef, MapRefMut,
+    },
+    sys::{
+        bpf_map_delete_elem, bpf_map_get_fd_by_id, bpf_map_lookup_elem, bpf_map_update_elem,
+        bpf_obj_get,
+    },
 };
 
 /// An array of eBPF Maps
@@ -23,6 +30,8 @@ use crate::{
 #[doc(alias = "BPF_MAP_TYPE_ARRAY_OF_MAPS")]
 pub struct Array<T: Deref<Target = Map>> {
     pub(crate) inner: T,
+    inner_map_type: Option<u32>,
+    inner_map_btf: Option<InnerMapBtf>,
 }
 
 impl<T: Deref<Target = Map>> Array<T> {
@@ -46,7 +55,20 @@ impl<T: Deref<Target = Map>> Array<T> {
         }
         let _fd = map.fd_or_err()?;
 
-        Ok(Array { inner: map })
+        Ok(Array {
+            inner: map,
+            inner_map_type: None,
+            inner_map_btf: None,
+        })
+    }
+
+    /// Wraps `map`, recording `inner_map`'s type (and BTF key/value types, if present) as the
+    /// template [`set_checked`](Array::set_checked) validates later inserts against.
+    pub fn with_inner(map: T, inner_map: &Map) -> Result<Array<T>, MapError> {
+        let mut array = Array::new(map)?;
+        array.inner_map_type = Some(inner_map.obj.def.map_type);
+        array.inner_map_btf = InnerMapBtf::resolve(inner_map);
+        Ok(array)
     }
 
     /// An iterator over the indices of the array that point to a map. The iterator item type
@@ -88,13 +110,31 @@ impl<T: Deref<Target = Map>> Array<T> {
         })?;
         Ok(inner_fd as RawFd)
     }
+
+    /// Returns the map stored at the given index, reinterpreted as the concrete typed map `M`.
+    ///
+    /// Unlike [`get`](Array::get), the returned map owns its file descriptor: it is closed
+    /// automatically when the value is dropped, so the caller no longer has to remember to
+    /// `libc::close` it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds, [`MapError::SyscallError`]
+    /// if a syscall fails, or whatever error `M::try_from` returns if the inner map doesn't
+    /// match the requested type.
+    pub fn get_typed<M: TryFrom<MapRefMut, Error = MapError>>(
+        &self,
+        index: &u32,
+        flags: u64,
+    ) -> Result<M, MapError> {
+        let fd = self.get(index, flags)?;
+        super::owned_inner_map(fd)
+    }
 }
 
 impl<T: Deref<Target = Map> + DerefMut<Target = Map>> Array<T> {
-    /// Stores a map fd into the map.
-    pub fn set<I: IntoRawFd>(&mut self, index: u32, map: I, flags: u64) -> Result<(), MapError> {
+    fn update(&mut self, index: u32, map_fd: RawFd, flags: u64) -> Result<(), MapError> {
         let fd = self.inner.fd_or_err()?;
-        let map_fd = map.into_raw_fd();
         self.check_bounds(index)?;
         bpf_map_update_elem(fd, &index, &map_fd, flags).map_err(|(code, io_error)| {
             MapError::SyscallError {
@@ -110,6 +150,62 @@ impl<T: Deref<Target = Map> + DerefMut<Target = Map>> Array<T> {
         Ok(())
     }
 
+    /// Stores a map fd into the map.
+    pub fn set<I: IntoRawFd>(&mut self, index: u32, map: I, flags: u64) -> Result<(), MapError> {
+        self.update(index, map.into_raw_fd(), flags)
+    }
+
+    /// Stores the map pinned at `path` into the map.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MapError::OutOfBounds`] if `index` is out of bounds, [`MapError::SyscallError`]
+    /// if `bpf_obj_get` or `bpf_map_update_elem` fail.
+    pub fn set_pinned<P: AsRef<Path>>(
+        &mut self,
+        index: u32,
+        path: P,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let map_fd = bpf_obj_get(path.as_ref()).map_err(|(code, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_obj_get".to_owned(),
+                code,
+                io_error,
+            }
+        })?;
+        self.update(index, map_fd, flags)
+    }
+
+    /// Stores a map into the map, rejecting it if it doesn't match the inner map template
+    /// passed to [`with_inner`](Array::with_inner).
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`set`](Array::set), returns
+    /// [`MapError::InvalidMapType`] if `map`'s type doesn't match the inner map template, or
+    /// [`MapError::InnerTypeMismatch`] if both maps carry BTF info and their key/value types
+    /// aren't structurally equal.
+    pub fn set_checked<M: Deref<Target = Map> + IntoRawFd>(
+        &mut self,
+        index: u32,
+        map: M,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        if let Some(expected) = self.inner_map_type {
+            let map_type = map.obj.def.map_type;
+            if map_type != expected {
+                return Err(MapError::InvalidMapType { map_type });
+            }
+        }
+        if let Some(template) = &self.inner_map_btf {
+            if let Some(found) = InnerMapBtf::resolve(&map) {
+                template.check(&found)?;
+            }
+        }
+        self.set(index, map, flags)
+    }
+
     /// Removes the map stored at `index` from the map.
     pub fn delete(&mut self, index: &u32) -> Result<(), MapError> {
         let fd = self.inner.fd_or_err()?;
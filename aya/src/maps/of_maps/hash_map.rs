@@ -3,15 +3,17 @@ use std::{
     marker::PhantomData,
     ops::{Deref, DerefMut},
     os::unix::io::{IntoRawFd, RawFd},
+    path::Path,
 };
 
 use crate::{
     generated::bpf_map_type::BPF_MAP_TYPE_HASH_OF_MAPS,
     maps::{
-        hash_map, of_maps::MapOfMaps, IterableMap, Map, MapError, MapIter, MapKeys, MapRef,
-        MapRefMut,
+        hash_map,
+        of_maps::{InnerMapBtf, MapOfMaps},
+        IterableMap, Map, MapError, MapIter, MapKeys, MapRef, MapRefMut,
     },
-    sys::{bpf_map_get_fd_by_id, bpf_map_lookup_elem},
+    sys::{bpf_map_get_fd_by_id, bpf_map_lookup_elem, bpf_obj_get},
     Pod,
 };
 
@@ -26,6 +28,8 @@ use crate::{
 pub struct HashMap<T: Deref<Target = Map>, K> {
     inner: T,
     _k: PhantomData<K>,
+    inner_map_type: Option<u32>,
+    inner_map_btf: Option<InnerMapBtf>,
 }
 
 impl<T: Deref<Target = Map>, K: Pod> HashMap<T, K> {
@@ -44,9 +48,20 @@ impl<T: Deref<Target = Map>, K: Pod> HashMap<T, K> {
         Ok(HashMap {
             inner: map,
             _k: PhantomData,
+            inner_map_type: None,
+            inner_map_btf: None,
         })
     }
 
+    /// Wraps `map`, recording `inner_map`'s type (and BTF key/value types, if present) as the
+    /// template [`insert_checked`](HashMap::insert_checked) validates later inserts against.
+    pub fn with_inner(map: T, inner_map: &Map) -> Result<HashMap<T, K>, MapError> {
+        let mut hash_map = HashMap::new(map)?;
+        hash_map.inner_map_type = Some(inner_map.obj.def.map_type);
+        hash_map.inner_map_btf = InnerMapBtf::resolve(inner_map);
+        Ok(hash_map)
+    }
+
     /// Returns the fd of the map stored at the given key.
     pub unsafe fn get(&self, key: &K, flags: u64) -> Result<RawFd, MapError> {
         let fd = self.inner.deref().fd_or_err()?;
@@ -76,6 +91,21 @@ impl<T: Deref<Target = Map>, K: Pod> HashMap<T, K> {
     pub unsafe fn keys(&self) -> MapKeys<'_, K> {
         MapKeys::new(&self.inner)
     }
+
+    /// Returns the map stored at the given key, reinterpreted as the concrete typed map `M`.
+    ///
+    /// Unlike [`get`](HashMap::get), the returned map owns its file descriptor: it is closed
+    /// automatically when the value is dropped, so the caller no longer has to remember to
+    /// `libc::close` it. This also means, unlike `get`/`iter`, calling it does not leak a fd
+    /// per element.
+    pub fn get_typed<M: TryFrom<MapRefMut, Error = MapError>>(
+        &self,
+        key: &K,
+        flags: u64,
+    ) -> Result<M, MapError> {
+        let fd = unsafe { self.get(key, flags) }?;
+        super::owned_inner_map(fd)
+    }
 }
 
 impl<T: DerefMut<Target = Map>, K: Pod> HashMap<T, K> {
@@ -90,6 +120,62 @@ impl<T: DerefMut<Target = Map>, K: Pod> HashMap<T, K> {
         Ok(())
     }
 
+    /// Inserts a map under the given key, rejecting it if it doesn't match the inner map
+    /// template passed to [`with_inner`](HashMap::with_inner).
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`insert`](HashMap::insert), returns
+    /// [`MapError::InvalidMapType`] if `value`'s type doesn't match the inner map template, or
+    /// [`MapError::InnerTypeMismatch`] if both maps carry BTF info and their key/value types
+    /// aren't structurally equal.
+    pub fn insert_checked<I: Deref<Target = Map> + IntoRawFd>(
+        &mut self,
+        key: K,
+        value: I,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        if let Some(expected) = self.inner_map_type {
+            let map_type = value.obj.def.map_type;
+            if map_type != expected {
+                return Err(MapError::InvalidMapType { map_type });
+            }
+        }
+        if let Some(template) = &self.inner_map_btf {
+            if let Some(found) = InnerMapBtf::resolve(&value) {
+                template.check(&found)?;
+            }
+        }
+        self.insert(key, value, flags)
+    }
+
+    /// Inserts the map pinned at `path` under the given key.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`insert`](HashMap::insert), returns
+    /// [`MapError::SyscallError`] if `bpf_obj_get` fails.
+    pub fn insert_pinned<P: AsRef<Path>>(
+        &mut self,
+        key: K,
+        path: P,
+        flags: u64,
+    ) -> Result<(), MapError> {
+        let map_fd = bpf_obj_get(path.as_ref()).map_err(|(code, io_error)| {
+            MapError::SyscallError {
+                call: "bpf_obj_get".to_owned(),
+                code,
+                io_error,
+            }
+        })?;
+        hash_map::insert(&mut self.inner, key, map_fd, flags)?;
+        // safety: we're closing a RawFd which we have ownership of
+        // this is required because inserting this in to the map causes
+        // there to be a reference to the map in both kernel and userspace
+        unsafe { libc::close(map_fd) };
+        Ok(())
+    }
+
     /// Removes a map from the map.
     pub fn remove(&mut self, key: &K) -> Result<(), MapError> {
         hash_map::remove(&mut self.inner, key)
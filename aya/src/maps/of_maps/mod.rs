@@ -2,9 +2,12 @@
 mod array;
 mod hash_map;
 
-use std::os::unix::io::RawFd;
+use std::{convert::TryFrom, os::unix::io::RawFd};
 
-use crate::maps::MapError;
+use crate::{
+    maps::{Map, MapError, MapRefMut},
+    obj::btf::{Btf, BtfType},
+};
 
 pub use array::Array;
 pub use hash_map::HashMap;
@@ -14,3 +17,115 @@ pub trait MapOfMaps {
     /// Dummy documentation
     fn fd_or_err(&self) -> Result<RawFd, MapError>;
 }
+
+/// Takes ownership of `fd`, wrapping it in a [`Map`] that closes it on drop, and reinterprets
+/// it as the concrete typed map `M`.
+///
+/// This is the building block behind the `get_typed` helpers on [`Array`] and [`HashMap`]: it
+/// turns a bare fd returned by `bpf_map_get_fd_by_id` into a safe, owned handle instead of
+/// leaving the caller to `libc::close` it.
+pub(crate) fn owned_inner_map<M: TryFrom<MapRefMut, Error = MapError>>(
+    fd: RawFd,
+) -> Result<M, MapError> {
+    let map = Map::from_fd(fd)?;
+    M::try_from(MapRefMut::new(map))
+}
+
+/// The BTF key/value type ids recorded as the template for an inner map, resolved from the
+/// `.BTF` section of the object the map was loaded from.
+///
+/// [`Array::set_checked`](array::Array::set_checked) and
+/// [`HashMap::insert_checked`](hash_map::HashMap::insert_checked) use this to require that a
+/// map inserted into a map-of-maps structurally matches the template recorded for the outer
+/// map, instead of letting a later lookup silently reinterpret its bytes.
+#[derive(Clone)]
+pub(crate) struct InnerMapBtf {
+    btf: Btf,
+    key_type_id: u32,
+    value_type_id: u32,
+}
+
+impl InnerMapBtf {
+    /// Resolves the BTF key/value type ids recorded on `map`, if the object it was loaded from
+    /// carries BTF info for it.
+    pub(crate) fn resolve(map: &Map) -> Option<InnerMapBtf> {
+        Some(InnerMapBtf {
+            btf: map.obj.btf.clone()?,
+            key_type_id: map.obj.btf_key_type_id?,
+            value_type_id: map.obj.btf_value_type_id?,
+        })
+    }
+
+    /// Requires that `other`'s key and value types are structurally equal to this template's,
+    /// after following `typedef`/`const`/`volatile` qualifiers: structs must have the same
+    /// size and the same member names and member types, in order; ints and enums the same
+    /// size; arrays the same length and element type. Only these four [`BtfType`] kinds are
+    /// compared: two types of any other kind (a union or a pointer, for example) are always
+    /// reported as a mismatch.
+    pub(crate) fn check(&self, other: &InnerMapBtf) -> Result<(), MapError> {
+        check_type_eq(&self.btf, self.key_type_id, &other.btf, other.key_type_id)?;
+        check_type_eq(&self.btf, self.value_type_id, &other.btf, other.value_type_id)
+    }
+}
+
+// `MapError::InnerTypeMismatch { expected, found }` is a new variant on the crate's existing
+// `MapError` enum (`aya/src/maps/mod.rs`), not touched by this series.
+fn check_type_eq(
+    expected_btf: &Btf,
+    expected_id: u32,
+    found_btf: &Btf,
+    found_id: u32,
+) -> Result<(), MapError> {
+    let expected = resolve(expected_btf, expected_id)?;
+    let found = resolve(found_btf, found_id)?;
+    if !types_structurally_eq(expected_btf, &expected, found_btf, &found) {
+        return Err(MapError::InnerTypeMismatch {
+            expected: expected_btf.type_name(&expected),
+            found: found_btf.type_name(&found),
+        });
+    }
+    Ok(())
+}
+
+/// Resolves `type_id` to its [`BtfType`], following through `typedef`/`const`/`volatile`
+/// qualifiers to the underlying type they wrap. Clang routinely wraps map key/value structs in
+/// one or more of these, so comparing the raw, possibly-qualified type would reject otherwise
+/// identical types.
+fn resolve(btf: &Btf, type_id: u32) -> Result<BtfType, MapError> {
+    let mut ty = btf.type_by_id(type_id)?;
+    loop {
+        let inner_id = match &ty {
+            BtfType::Typedef(t) => t.btf_type,
+            BtfType::Const(t) => t.btf_type,
+            BtfType::Volatile(t) => t.btf_type,
+            _ => return Ok(ty),
+        };
+        ty = btf.type_by_id(inner_id)?;
+    }
+}
+
+fn types_structurally_eq(a_btf: &Btf, a: &BtfType, b_btf: &Btf, b: &BtfType) -> bool {
+    match (a, b) {
+        (BtfType::Struct(a), BtfType::Struct(b)) => {
+            a.size() == b.size()
+                && a.members.len() == b.members.len()
+                && a.members.iter().zip(b.members.iter()).all(|(am, bm)| {
+                    am.name(a_btf) == bm.name(b_btf)
+                        && match (resolve(a_btf, am.btf_type), resolve(b_btf, bm.btf_type)) {
+                            (Ok(at), Ok(bt)) => types_structurally_eq(a_btf, &at, b_btf, &bt),
+                            _ => false,
+                        }
+                })
+        }
+        (BtfType::Int(a), BtfType::Int(b)) => a.size() == b.size(),
+        (BtfType::Enum(a), BtfType::Enum(b)) => a.size() == b.size(),
+        (BtfType::Array(a), BtfType::Array(b)) => {
+            a.len == b.len
+                && match (resolve(a_btf, a.element_type), resolve(b_btf, b.element_type)) {
+                    (Ok(at), Ok(bt)) => types_structurally_eq(a_btf, &at, b_btf, &bt),
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
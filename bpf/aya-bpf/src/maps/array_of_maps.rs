@@ -8,9 +8,10 @@ use crate::{
     maps::PinningType,
 };
 
-#[repr(transparent)]
+#[repr(C)]
 pub struct ArrayOfMaps {
     def: bpf_map_def,
+    inner_map_idx: u32,
 }
 
 impl ArrayOfMaps {
@@ -25,6 +26,7 @@ impl ArrayOfMaps {
                 id: 0,
                 pinning: PinningType::None as u32,
             },
+            inner_map_idx: 0,
         }
     }
 
@@ -39,9 +41,41 @@ impl ArrayOfMaps {
                 id: 0,
                 pinning: PinningType::ByName as u32,
             },
+            inner_map_idx: 0,
         }
     }
 
+    /// Creates an array of maps that records `inner_map_idx` as the template for the inner
+    /// maps it stores.
+    ///
+    /// `inner_map_idx` is the index, within the object's `maps` section, of the map definition
+    /// to use as the prototype. Recording it here is necessary but not sufficient: the kernel
+    /// also requires an `inner_map_fd` at `BPF_MAP_CREATE` time for
+    /// `BPF_MAP_TYPE_ARRAY_OF_MAPS` to pass validation. The userspace loader still has to read
+    /// it back with [`inner_map_idx`](ArrayOfMaps::inner_map_idx), resolve it to the
+    /// already-created fd of that map, and pass it along as `inner_map_fd`; until that's wired
+    /// up, map-of-maps creation will still fail kernel validation.
+    pub const fn with_inner(max_entries: u32, flags: u32, inner_map_idx: u32) -> ArrayOfMaps {
+        ArrayOfMaps {
+            def: bpf_map_def {
+                type_: BPF_MAP_TYPE_ARRAY_OF_MAPS,
+                key_size: mem::size_of::<u32>() as u32,
+                value_size: mem::size_of::<u32>() as u32,
+                max_entries,
+                map_flags: flags,
+                id: 0,
+                pinning: PinningType::None as u32,
+            },
+            inner_map_idx,
+        }
+    }
+
+    /// Returns the `inner_map_idx` recorded by [`with_inner`](ArrayOfMaps::with_inner), for the
+    /// userspace loader to resolve to an `inner_map_fd` at `BPF_MAP_CREATE` time.
+    pub fn inner_map_idx(&self) -> u32 {
+        self.inner_map_idx
+    }
+
     pub unsafe fn get(&mut self, index: u32) -> Option<&u32> {
         let value = bpf_map_lookup_elem(
             &mut self.def as *mut _ as *mut _,